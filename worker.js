@@ -1,7 +1,7 @@
 // CodeTease - VeghJS Worker
 // "The Thread" - Handles heavy lifting off-main-thread.
 
-import init, { VeghStreamingHasher, get_metadata, list_files, check_cache_hit, get_file_content } from "./pkg/vegh_js.js";
+import init, { VeghStreamingHasher, get_metadata, list_files, check_cache_hit, get_file_content, verify_integrity } from "./pkg/vegh_js.js";
 
 let isReady = false;
 
@@ -37,8 +37,8 @@ self.onmessage = async (e) => {
                 break;
 
             case 'LIST_FILES':
-                // Payload: { file }
-                await handleListFiles(payload.file);
+                // Payload: { file, includeMetadata, skipHidden, strictPathEncoding }
+                await handleListFiles(payload.file, payload.includeMetadata, payload.skipHidden, payload.strictPathEncoding, payload.onDuplicate);
                 break;
             
             case 'CHECK_CACHE':
@@ -50,7 +50,12 @@ self.onmessage = async (e) => {
             case 'GET_FILE_CONTENT':
                 // [NEW] Extract specific file content
                 // Payload: { file, path }
-                await handleGetFileContent(payload.file, payload.path);
+                await handleGetFileContent(payload.file, payload.path, payload.onDuplicate);
+                break;
+
+            case 'VERIFY_INTEGRITY':
+                // Payload: { file } - checks files against the embedded .vegh.hashes.json
+                await handleVerifyIntegrity(payload.file);
                 break;
 
             default:
@@ -98,16 +103,16 @@ async function handleMetadata(file) {
     postMessage({ type: 'RESULT_METADATA', payload: meta });
 }
 
-async function handleListFiles(file) {
+async function handleListFiles(file, includeMetadata = false, skipHidden = false, strictPathEncoding = false, onDuplicate = undefined) {
     const buffer = await file.arrayBuffer();
-    const list = list_files(new Uint8Array(buffer));
+    const list = list_files(new Uint8Array(buffer), includeMetadata, skipHidden, strictPathEncoding, onDuplicate);
     postMessage({ type: 'RESULT_FILES', payload: list });
 }
 
 // [NEW] Handler for extracting content
-async function handleGetFileContent(file, targetPath) {
+async function handleGetFileContent(file, targetPath, onDuplicate = undefined) {
     const buffer = await file.arrayBuffer();
-    const content = get_file_content(new Uint8Array(buffer), targetPath);
+    const content = get_file_content(new Uint8Array(buffer), targetPath, onDuplicate);
     
     // Transferable objects optimization can be applied here if needed
     postMessage({ 
@@ -117,4 +122,13 @@ async function handleGetFileContent(file, targetPath) {
             data: content // This is a Uint8Array
         } 
     });
-}
\ No newline at end of file
+}
+
+async function handleVerifyIntegrity(file) {
+    const buffer = await file.arrayBuffer();
+    const report = verify_integrity(new Uint8Array(buffer), (path, done, total, matched, mismatched) => {
+        const progress = total > 0 ? (done / total) * 100 : 100;
+        postMessage({ type: 'PROGRESS', payload: { task: 'verify_integrity', progress, path, matched, mismatched } });
+    });
+    postMessage({ type: 'RESULT_VERIFY', payload: report });
+}